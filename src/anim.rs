@@ -0,0 +1,53 @@
+use anyhow::{Context, Result};
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::{AnimationDecoder, RgbaImage};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::time::Duration;
+
+/// Decode an animated GIF or APNG into its frames and their display durations. Returns `None`
+/// for anything else (including plain, single-frame GIFs/PNGs), so the caller can fall back to
+/// treating the file as a still image.
+pub fn decode_frames(path: &Path) -> Result<Option<Vec<(RgbaImage, Duration)>>> {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    let frames = match ext.as_str() {
+        "gif" => {
+            let decoder = GifDecoder::new(BufReader::new(File::open(path)?))
+                .with_context(|| "Couldn't create GIF decoder")?;
+            collect_frames(decoder)?
+        }
+        "png" | "apng" => {
+            let decoder = PngDecoder::new(BufReader::new(File::open(path)?))
+                .with_context(|| "Couldn't create PNG decoder")?;
+            if !decoder.is_apng() {
+                return Ok(None);
+            }
+            collect_frames(decoder.apng())?
+        }
+        _ => return Ok(None),
+    };
+
+    if frames.len() <= 1 {
+        return Ok(None);
+    }
+
+    Ok(Some(frames))
+}
+
+fn collect_frames<'a, D: AnimationDecoder<'a>>(decoder: D) -> Result<Vec<(RgbaImage, Duration)>> {
+    decoder
+        .into_frames()
+        .map(|frame| {
+            let frame = frame.with_context(|| "Couldn't decode animation frame")?;
+            let delay = frame.delay().into();
+            Ok((frame.into_buffer(), delay))
+        })
+        .collect()
+}
@@ -0,0 +1,289 @@
+use crate::{get_current_window_id, load_display, rowcol_to_pixels, ImageDisplay};
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use structopt::StructOpt;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{Screen, Window};
+
+#[derive(StructOpt)]
+pub struct DaemonOpt {
+    /// Unix socket to listen on for `show`/`move`/`hide`/`clear` commands, one per line
+    #[structopt(long, default_value = "/tmp/termimg.sock")]
+    socket: PathBuf,
+}
+
+/// Images currently managed by the daemon, keyed by the id the client chose for them.
+type Images = HashMap<String, ImageDisplay<'static>>;
+
+/// One line of the control protocol, already split into its fields.
+enum Command {
+    Show {
+        id: String,
+        path: PathBuf,
+        row: i16,
+        col: i16,
+    },
+    Move {
+        id: String,
+        row: i16,
+        col: i16,
+    },
+    Hide {
+        id: String,
+    },
+    Clear,
+}
+
+fn parse_command(line: &str) -> Result<Command> {
+    let mut words = line.split_whitespace();
+    match words.next() {
+        Some("show") => Ok(Command::Show {
+            id: words.next().with_context(|| "`show` needs an id")?.into(),
+            path: words.next().with_context(|| "`show` needs a path")?.into(),
+            row: words
+                .next()
+                .with_context(|| "`show` needs a row")?
+                .parse()?,
+            col: words
+                .next()
+                .with_context(|| "`show` needs a col")?
+                .parse()?,
+        }),
+        Some("move") => Ok(Command::Move {
+            id: words.next().with_context(|| "`move` needs an id")?.into(),
+            row: words
+                .next()
+                .with_context(|| "`move` needs a row")?
+                .parse()?,
+            col: words
+                .next()
+                .with_context(|| "`move` needs a col")?
+                .parse()?,
+        }),
+        Some("hide") => Ok(Command::Hide {
+            id: words.next().with_context(|| "`hide` needs an id")?.into(),
+        }),
+        Some("clear") => Ok(Command::Clear),
+        Some(other) => bail!("Unknown command {:?}", other),
+        None => bail!("Empty command"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_show() {
+        let command = parse_command("show foo /tmp/foo.png 3 5").unwrap();
+        assert!(matches!(
+            command,
+            Command::Show { id, path, row: 3, col: 5 }
+                if id == "foo" && path == PathBuf::from("/tmp/foo.png")
+        ));
+    }
+
+    #[test]
+    fn parses_move() {
+        let command = parse_command("move foo 3 5").unwrap();
+        assert!(matches!(
+            command,
+            Command::Move { id, row: 3, col: 5 } if id == "foo"
+        ));
+    }
+
+    #[test]
+    fn parses_hide() {
+        let command = parse_command("hide foo").unwrap();
+        assert!(matches!(command, Command::Hide { id } if id == "foo"));
+    }
+
+    #[test]
+    fn parses_clear() {
+        assert!(matches!(parse_command("clear").unwrap(), Command::Clear));
+    }
+
+    #[test]
+    fn rejects_unknown_command() {
+        assert!(parse_command("frobnicate foo").is_err());
+    }
+
+    #[test]
+    fn rejects_empty_line() {
+        assert!(parse_command("").is_err());
+    }
+
+    #[test]
+    fn rejects_show_missing_fields() {
+        assert!(parse_command("show foo /tmp/foo.png").is_err());
+    }
+
+    #[test]
+    fn rejects_show_non_integer_row() {
+        assert!(parse_command("show foo /tmp/foo.png x 5").is_err());
+    }
+}
+
+/// Run one command against the managed images, returning the line to write back to the client.
+fn apply_command(
+    conn: &impl Connection,
+    screen: &Screen,
+    window: Window,
+    images: &mut Images,
+    command: Command,
+) -> Result<String> {
+    match command {
+        Command::Show { id, path, row, col } => {
+            if let Some(mut old) = images.remove(&id) {
+                if old.is_shown() {
+                    old.remove(conn)?;
+                }
+            }
+            let row_col = (row, col);
+            let mut display = load_display(conn, screen, &path, window, None, None, row_col)?;
+            let (x, y) = rowcol_to_pixels(conn, window, row_col)?;
+            display.show_at(conn, screen, (x, y))?;
+            images.insert(id, display);
+            Ok("ok".to_string())
+        }
+        Command::Move { id, row, col } => {
+            let display = images
+                .get_mut(&id)
+                .with_context(|| format!("No image shown with id {:?}", id))?;
+            display.reposition(conn, screen, (row, col))?;
+            Ok("ok".to_string())
+        }
+        Command::Hide { id } => {
+            let mut display = images
+                .remove(&id)
+                .with_context(|| format!("No image shown with id {:?}", id))?;
+            if display.is_shown() {
+                display.remove(conn)?;
+            }
+            Ok("ok".to_string())
+        }
+        Command::Clear => {
+            for (_, mut display) in images.drain() {
+                if display.is_shown() {
+                    display.remove(conn)?;
+                }
+            }
+            Ok("ok".to_string())
+        }
+    }
+}
+
+/// Accept every connection currently pending on `listener`, and run the one command each of them
+/// sends before replying and closing.
+fn handle_pending_connections(
+    conn: &impl Connection,
+    screen: &Screen,
+    window: Window,
+    listener: &UnixListener,
+    images: &mut Images,
+) -> Result<()> {
+    loop {
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => return Ok(()),
+            Err(err) => return Err(err).with_context(|| "Couldn't accept a socket connection"),
+        };
+        handle_connection(conn, screen, window, stream, images);
+    }
+}
+
+/// Read a single command line from `stream`, apply it, and write back a one-line response.
+/// Errors are sent back to the client as an `error: ...` line (and a failed write is ignored)
+/// rather than propagated, so one bad client can't take the daemon down.
+fn handle_connection(
+    conn: &impl Connection,
+    screen: &Screen,
+    window: Window,
+    stream: UnixStream,
+    images: &mut Images,
+) {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    let response = match reader.read_line(&mut line) {
+        Ok(0) => return, // client disconnected without sending anything
+        Ok(_) => parse_command(line.trim_end())
+            .and_then(|command| apply_command(conn, screen, window, images, command)),
+        Err(err) => Err(err).with_context(|| "Couldn't read from socket"),
+    };
+
+    let response = match response {
+        Ok(line) => line,
+        Err(err) => format!("error: {:#}", err),
+    };
+    let stream = reader.get_mut();
+    let _ = writeln!(stream, "{}", response);
+}
+
+/// Open a single X connection and keep watching it (for animation/resize/teardown events,
+/// handled by `ImageDisplay::handle_event`, as in the one-shot `show` mode) alongside a Unix socket carrying show/move/hide/clear
+/// commands for any number of images over the one terminal window that was focused on startup.
+pub fn run(opt: DaemonOpt) -> Result<()> {
+    let (conn, screen_num) = x11rb::connect(None).with_context(|| "Couldn't connect to X")?;
+    let screen = &conn.setup().roots[screen_num];
+    let window = get_current_window_id(&conn, screen)?;
+
+    let _ = std::fs::remove_file(&opt.socket);
+    let listener = UnixListener::bind(&opt.socket)
+        .with_context(|| format!("Couldn't bind socket at {:?}", opt.socket))?;
+    listener
+        .set_nonblocking(true)
+        .with_context(|| "Couldn't make the socket non-blocking")?;
+
+    let mut images: Images = HashMap::new();
+
+    let x_fd = conn.stream().as_raw_fd();
+    let listener_fd = listener.as_raw_fd();
+    let mut pollfds = [
+        libc::pollfd {
+            fd: x_fd,
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: listener_fd,
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+
+    loop {
+        pollfds[0].revents = 0;
+        pollfds[1].revents = 0;
+        let ready = unsafe { libc::poll(pollfds.as_mut_ptr(), pollfds.len() as libc::nfds_t, -1) };
+        if ready < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err).with_context(|| "poll() on the X connection and socket failed");
+        }
+
+        if pollfds[0].revents & libc::POLLIN != 0 {
+            let mut terminal_gone = false;
+            while let Some(event) = conn.poll_for_event()? {
+                for display in images.values_mut() {
+                    if display.handle_event(&conn, screen, event.clone())? {
+                        terminal_gone = true;
+                    }
+                }
+            }
+            if terminal_gone {
+                images.clear();
+                return Ok(());
+            }
+        }
+
+        if pollfds[1].revents & libc::POLLIN != 0 {
+            handle_pending_connections(&conn, screen, window, &listener, &mut images)?;
+        }
+    }
+}
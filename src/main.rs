@@ -1,32 +1,78 @@
-use anyhow::{Context, Error, Result};
+mod anim;
+mod daemon;
+mod present;
+mod render;
+
+use anyhow::{bail, Context, Error, Result};
 use image::io::Reader as ImageReader;
-use image::RgbImage;
+use image::{RgbImage, Rgba, RgbaImage};
 use std::borrow::Cow;
-use std::path::PathBuf;
-use std::process::Command;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::Duration;
 use structopt::StructOpt;
 use x11rb::{
     connection::Connection,
     image as x11image,
+    protocol::present::{ConnectionExt as _, EventMask as PresentEventMask},
+    protocol::render::{ConnectionExt as _, CreatePictureAux, PictOp, Transform},
     protocol::xproto::{
-        ConnectionExt, CreateGCAux, CreateWindowAux, Screen, VisualClass, Visualid, Window,
-        WindowClass,
+        AtomEnum, ChangeWindowAttributesAux, ColormapAlloc, ConfigureWindowAux, ConnectionExt,
+        CreateGCAux, CreateWindowAux, EventMask, Gcontext, GrabMode, Pixmap, Rectangle, Screen,
+        SubwindowMode, VisualClass, Visualid, Window, WindowClass, GX,
     },
+    protocol::Event,
 };
 
+/// Write end of the self-pipe used to wake `run_show`'s poll loop on SIGINT. A plain flag isn't
+/// enough: a signal arriving between checking the flag and entering `poll()` would go unnoticed
+/// until the next X event, so there has to be an fd to actually poll on. Set once, right before the signal handler is installed; -1 means "not installed yet".
+static SIGINT_PIPE_WRITE_FD: AtomicI32 = AtomicI32::new(-1);
+
 #[derive(StructOpt)]
 struct Opt {
     /// Path to image file
     #[structopt(parse(from_os_str))]
     image_file: PathBuf,
 
-    /// Terminal row to display the image in
+    /// Terminal row to display the image in. Required unless `--select` is passed.
     #[structopt()]
-    row: i16,
+    row: Option<i16>,
 
-    /// Terminal column to display the image in
+    /// Terminal column to display the image in. Required unless `--select` is passed.
     #[structopt()]
-    col: i16,
+    col: Option<i16>,
+
+    /// Width to scale the image to, in terminal columns (defaults to the image's native pixel
+    /// width)
+    #[structopt(long)]
+    width: Option<u16>,
+
+    /// Height to scale the image to, in terminal rows (defaults to the image's native pixel
+    /// height)
+    #[structopt(long)]
+    height: Option<u16>,
+
+    /// Flatten transparent images onto this solid background color (as a hex RRGGBB string)
+    /// instead of compositing them over the terminal
+    #[structopt(long, parse(try_from_str = parse_hex_color))]
+    background: Option<Rgba<u8>>,
+
+    /// Instead of `row`/`col`, drag out a rectangle on screen to place (and size) the image in
+    #[structopt(long)]
+    select: bool,
+}
+
+/// Parse a `RRGGBB` hex string into an opaque color.
+fn parse_hex_color(s: &str) -> Result<Rgba<u8>> {
+    if s.len() != 6 || !s.is_ascii() {
+        bail!("Expected a 6-digit hex color (RRGGBB), got {:?}", s);
+    }
+    let r = u8::from_str_radix(&s[0..2], 16)?;
+    let g = u8::from_str_radix(&s[2..4], 16)?;
+    let b = u8::from_str_radix(&s[4..6], 16)?;
+    Ok(Rgba([r, g, b, 255]))
 }
 
 /// Taken from https://github.com/psychon/x11rb/blob/84a877d72b87ac4de82aa77c4cfc0598ed41732a/examples/display_ppm.rs#L73-L107
@@ -66,23 +112,60 @@ fn check_visual(screen: &Screen, id: Visualid) -> Result<x11image::PixelLayout>
     Ok(result)
 }
 
-fn get_current_window_id() -> Result<Window> {
-    let output = Command::new("xdotool")
-        .arg("getwindowfocus")
-        .output()
-        .with_context(|| "Failed to run xdotool")?;
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    if output.status.success() {
-        stdout
-            .trim()
-            .parse()
-            .with_context(|| "Couldn't parse window ID number from xdotool")
+/// Query the window manager (via the `_NET_ACTIVE_WINDOW` EWMH hint on the root window) for the
+/// currently focused window. Falls back to `GetInputFocus` if the WM doesn't publish the hint, so
+/// this also works under bare/non-EWMH window managers.
+fn get_current_window_id(conn: &impl Connection, screen: &Screen) -> Result<Window> {
+    let net_active_window = conn
+        .intern_atom(false, b"_NET_ACTIVE_WINDOW")?
+        .reply()
+        .with_context(|| "Couldn't intern _NET_ACTIVE_WINDOW atom")?
+        .atom;
+
+    let reply = conn
+        .get_property(
+            false,
+            screen.root,
+            net_active_window,
+            AtomEnum::WINDOW,
+            0,
+            1,
+        )?
+        .reply()
+        .with_context(|| "Couldn't read _NET_ACTIVE_WINDOW property")?;
+
+    if let Some(window) = reply.value32().and_then(|mut it| it.next()) {
+        if window != 0 {
+            return Ok(window);
+        }
+    }
+
+    // No EWMH-compliant WM published a (non-null) _NET_ACTIVE_WINDOW. Fall back to asking the
+    // server directly which window has input focus.
+    let focus = conn
+        .get_input_focus()?
+        .reply()
+        .with_context(|| "Couldn't query input focus")?
+        .focus;
+    // `focus` can also legitimately be `PointerRoot` (1), not a real window, under WMs that don't
+    // focus a specific client window (e.g. no WM at all).
+    if focus != 0 && focus != 1 && focus != screen.root {
+        Ok(focus)
     } else {
-        Err(Error::msg(String::from_utf8(output.stderr)?))
-            .with_context(|| "xdotool exited with non-zero status")
+        Err(Error::msg(
+            "No _NET_ACTIVE_WINDOW hint and no focused window; is an EWMH-compliant WM running?",
+        ))
     }
 }
 
+/// Size of a single terminal cell, in pixels, as `(pixels_per_col, pixels_per_row)`.
+fn cell_pixel_size() -> Result<(i16, i16)> {
+    let (cols, rows) = termion::terminal_size().with_context(|| "Could not get terminal size")?;
+    let (xpixels, ypixels) =
+        termion::terminal_size_pixels().with_context(|| "Could not get terminal size in pixels")?;
+    Ok(((xpixels / cols) as i16, (ypixels / rows) as i16))
+}
+
 fn rowcol_to_pixels(
     conn: &impl Connection,
     window: Window,
@@ -90,13 +173,10 @@ fn rowcol_to_pixels(
 ) -> Result<(i16, i16)> {
     // Get geometry of the given window
     let window_geometry = conn.get_geometry(window)?.reply()?;
-    dbg!(window_geometry);
 
-    let (cols, rows) = termion::terminal_size().with_context(|| "Could not get terminal size")?;
     let (xpixels, ypixels) =
         termion::terminal_size_pixels().with_context(|| "Could not get terminal size in pixels")?;
-    let pixels_per_row = (ypixels / rows) as i16;
-    let pixels_per_col = (xpixels / cols) as i16;
+    let (pixels_per_col, pixels_per_row) = cell_pixel_size()?;
     let yoffset = ((window_geometry.height - ypixels) as i16) / 2;
     let xoffset = ((window_geometry.width - xpixels) as i16) / 2;
 
@@ -106,11 +186,230 @@ fn rowcol_to_pixels(
     ))
 }
 
-struct ImageDisplay<'a> {
+/// The inverse of `rowcol_to_pixels`: translate a `window`-relative pixel coordinate back into
+/// the `(row, col)` that would have produced it.
+fn pixels_to_rowcol(
+    conn: &impl Connection,
+    window: Window,
+    (x, y): (i16, i16),
+) -> Result<(i16, i16)> {
+    let window_geometry = conn.get_geometry(window)?.reply()?;
+
+    let (xpixels, ypixels) =
+        termion::terminal_size_pixels().with_context(|| "Could not get terminal size in pixels")?;
+    let (pixels_per_col, pixels_per_row) = cell_pixel_size()?;
+    let yoffset = ((window_geometry.height - ypixels) as i16) / 2;
+    let xoffset = ((window_geometry.width - xpixels) as i16) / 2;
+
+    Ok((
+        (y - yoffset) / pixels_per_row,
+        (x - xoffset) / pixels_per_col,
+    ))
+}
+
+/// Keycode Escape maps to under virtually every XKB layout in practice. Good enough to let
+/// `--select` be cancelled without pulling in a full keysym table for one binding.
+const ESCAPE_KEYCODE: u8 = 9;
+
+/// Let the user drag out a rectangle on the root window — grabbing the pointer and keyboard so
+/// the drag doesn't leak clicks/keys to whatever's underneath — and translate the result into a
+/// `(row, col)` (relative to `window`, via the inverse of `rowcol_to_pixels`) plus a pixel size,
+/// ready to hand to `ImageDisplay`. Escape cancels.
+fn select_region(
+    conn: &impl Connection,
+    screen: &Screen,
+    window: Window,
+) -> Result<((i16, i16), (u16, u16))> {
+    let root = screen.root;
+
+    conn.grab_pointer(
+        false,
+        root,
+        EventMask::BUTTON_PRESS | EventMask::BUTTON_RELEASE | EventMask::POINTER_MOTION,
+        GrabMode::ASYNC,
+        GrabMode::ASYNC,
+        root,
+        0u32, // cursor: keep the default
+        x11rb::CURRENT_TIME,
+    )?
+    .reply()
+    .with_context(|| "Couldn't grab the pointer for --select")?;
+    conn.grab_keyboard(
+        false,
+        root,
+        x11rb::CURRENT_TIME,
+        GrabMode::ASYNC,
+        GrabMode::ASYNC,
+    )?
+    .reply()
+    .with_context(|| "Couldn't grab the keyboard for --select")?;
+
+    let gc = conn.generate_id()?;
+    conn.create_gc(
+        gc,
+        root,
+        &CreateGCAux::new()
+            .function(GX::XOR)
+            .foreground(screen.white_pixel ^ screen.black_pixel)
+            .subwindow_mode(SubwindowMode::INCLUDE_INFERIORS)
+            .graphics_exposures(0),
+    )?;
+
+    let mut anchor: Option<(i16, i16)> = None;
+    let mut last: Option<(i16, i16)> = None;
+    let selection = loop {
+        match conn.wait_for_event()? {
+            Event::ButtonPress(ev) => {
+                anchor = Some((ev.root_x, ev.root_y));
+                last = anchor;
+            }
+            Event::MotionNotify(ev) => {
+                if let (Some(anchor), Some(last_point)) = (anchor, last) {
+                    draw_xor_rect(conn, root, gc, anchor, last_point)?; // undraw the old rectangle
+                    let here = (ev.root_x, ev.root_y);
+                    draw_xor_rect(conn, root, gc, anchor, here)?;
+                    last = Some(here);
+                }
+            }
+            Event::ButtonRelease(ev) => {
+                if let Some(anchor) = anchor {
+                    let here = (ev.root_x, ev.root_y);
+                    draw_xor_rect(conn, root, gc, anchor, last.unwrap_or(here))?; // undraw
+                    break Some((anchor, here));
+                }
+            }
+            Event::KeyPress(ev) if ev.detail == ESCAPE_KEYCODE => {
+                if let (Some(anchor), Some(last_point)) = (anchor, last) {
+                    draw_xor_rect(conn, root, gc, anchor, last_point)?; // undraw
+                }
+                break None;
+            }
+            _ => {}
+        }
+    };
+
+    conn.ungrab_pointer(x11rb::CURRENT_TIME)?;
+    conn.ungrab_keyboard(x11rb::CURRENT_TIME)?;
+    conn.free_gc(gc)?;
+    conn.flush()?;
+
+    let ((ax, ay), (bx, by)) = selection.with_context(|| "Selection cancelled")?;
+    let (x, y) = (ax.min(bx), ay.min(by));
+    let (width, height) = (ax.abs_diff(bx), ay.abs_diff(by));
+    if width == 0 || height == 0 {
+        bail!("Selection is empty");
+    }
+
+    // The drag was tracked in root coordinates; `pixels_to_rowcol` wants `window`-relative ones.
+    let translated = conn
+        .translate_coordinates(root, window, x, y)?
+        .reply()
+        .with_context(|| "Couldn't translate the selection into window coordinates")?;
+    let row_col = pixels_to_rowcol(conn, window, (translated.dst_x, translated.dst_y))?;
+    Ok((row_col, (width, height)))
+}
+
+/// Draw (or, called again with the same points, undraw) a rectangle's outline on `drawable` using
+/// a `GXxor` GC, so rubber-banding a selection never needs to know what's underneath it.
+fn draw_xor_rect(
+    conn: &impl Connection,
+    drawable: Window,
+    gc: Gcontext,
+    (ax, ay): (i16, i16),
+    (bx, by): (i16, i16),
+) -> Result<()> {
+    let rect = Rectangle {
+        x: ax.min(bx),
+        y: ay.min(by),
+        width: ax.abs_diff(bx),
+        height: ay.abs_diff(by),
+    };
+    conn.poly_rectangle(drawable, gc, &[rect])?;
+    conn.flush()?;
+    Ok(())
+}
+
+/// A single frame of an (possibly one-frame) animation, together with how long it should stay on
+/// screen before the next frame is presented.
+struct Frame<'a> {
     image: Cow<'a, x11image::Image<'a>>,
+    delay: Duration,
+}
+
+/// Bookkeeping for a window that's currently on screen, including the state needed to drive
+/// Present-based animation playback.
+struct ShownState {
+    window: Window,
+    /// One pre-painted, display-sized pixmap per frame, presented in a loop via the Present
+    /// extension.
+    pixmaps: Vec<Pixmap>,
+    colormap: Option<x11rb::protocol::xproto::Colormap>,
+    /// The event ID registered with `present_select_input` for this window, used by `remove` to
+    /// deselect its Present events again; 0 for still images, which select none.
+    event_id: u32,
+    next_serial: u32,
+    current_frame: usize,
+    msc_clock: present::MscClock,
+}
+
+/// Blend a straight-alpha channel value `c` (with coverage `a`, 0-255) over a background channel.
+fn blend_channel(c: u8, bg: u8, a: u16) -> u8 {
+    ((c as u16 * a + bg as u16 * (255 - a)) / 255) as u8
+}
+
+/// Flatten a transparent image onto a solid background, for `--background`. The result is opaque
+/// (alpha 255 everywhere), so it can still go through the normal alpha-compositing display path.
+fn flatten_onto_background(image: &RgbaImage, background: Rgba<u8>) -> RgbaImage {
+    RgbaImage::from_fn(image.width(), image.height(), |x, y| {
+        let Rgba([r, g, b, a]) = *image.get_pixel(x, y);
+        let a = a as u16;
+        Rgba([
+            blend_channel(r, background[0], a),
+            blend_channel(g, background[1], a),
+            blend_channel(b, background[2], a),
+            255,
+        ])
+    })
+}
+
+/// Build a 32-bit x11rb `Image` in RENDER's standard ARGB32 layout from an RGBA buffer,
+/// premultiplying color by alpha as that format (and `PictOp::OVER`) expect.
+fn premultiplied_argb32_image(image: &RgbaImage) -> Result<x11image::Image<'static>> {
+    let (w, h) = image.dimensions();
+    let w = w as u16;
+    let h = h as u16;
+
+    let mut data = Vec::with_capacity(w as usize * h as usize * 4);
+    for Rgba([r, g, b, a]) in image.pixels().copied() {
+        let premultiply = |c: u8| ((c as u16 * a as u16) / 255) as u8;
+        data.extend_from_slice(&[premultiply(b), premultiply(g), premultiply(r), a]);
+    }
+
+    Ok(x11image::Image::new(
+        w,
+        h,
+        x11image::ScanlinePad::Pad32,
+        32,
+        x11image::BitsPerPixel::B32,
+        x11image::ImageOrder::LsbFirst,
+        data.into(),
+    )?)
+}
+
+struct ImageDisplay<'a> {
+    frames: Vec<Frame<'a>>,
+    /// Whether frames carry a (premultiplied) alpha channel and must be shown in a 32-bit window,
+    /// composited with `PictOp::OVER`.
+    has_alpha: bool,
     parent_window: Window,
+    /// Size, in pixels, to scale the image to when displaying it. `None` means the frames'
+    /// native pixel size.
+    target_size: Option<(u16, u16)>,
+    /// The `(row, col)` the image was asked to be shown at, kept around so the window can be
+    /// repositioned (via `rowcol_to_pixels`) whenever `parent_window` is resized or scrolled.
+    row_col: (i16, i16),
 
-    window: Option<Window>,
+    state: Option<ShownState>,
 }
 
 impl<'a> ImageDisplay<'a> {
@@ -119,6 +418,8 @@ impl<'a> ImageDisplay<'a> {
         screen: &Screen,
         image: RgbImage,
         parent_window: Window,
+        target_size: Option<(u16, u16)>,
+        row_col: (i16, i16),
     ) -> Result<Self> {
         // Get image information and create x11rb image
         let (w, h) = image.dimensions();
@@ -146,22 +447,101 @@ impl<'a> ImageDisplay<'a> {
             .into_owned();
 
         Ok(Self {
-            image: Cow::Owned(img),
+            frames: vec![Frame {
+                image: Cow::Owned(img),
+                delay: Duration::ZERO,
+            }],
+            has_alpha: false,
             parent_window,
-            window: None,
+            target_size,
+            row_col,
+            state: None,
+        })
+    }
+
+    /// Like `new`, but for images with an alpha channel. The image is shown in a 32-bit ARGB
+    /// window so that a compositing window manager can blend transparent regions with whatever
+    /// is behind the terminal.
+    fn new_rgba(
+        image: RgbaImage,
+        parent_window: Window,
+        target_size: Option<(u16, u16)>,
+        row_col: (i16, i16),
+    ) -> Result<Self> {
+        let img = premultiplied_argb32_image(&image)?;
+
+        Ok(Self {
+            frames: vec![Frame {
+                image: Cow::Owned(img),
+                delay: Duration::ZERO,
+            }],
+            has_alpha: true,
+            parent_window,
+            target_size,
+            row_col,
+            state: None,
+        })
+    }
+
+    /// Build a player for a multi-frame animation (animated GIF/APNG). Frames always carry an
+    /// alpha channel, since both formats support per-frame transparency.
+    fn new_animated(
+        frames: Vec<(RgbaImage, Duration)>,
+        parent_window: Window,
+        target_size: Option<(u16, u16)>,
+        row_col: (i16, i16),
+    ) -> Result<Self> {
+        let frames = frames
+            .into_iter()
+            .map(|(image, delay)| {
+                Ok(Frame {
+                    image: Cow::Owned(premultiplied_argb32_image(&image)?),
+                    delay,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            frames,
+            has_alpha: true,
+            parent_window,
+            target_size,
+            row_col,
+            state: None,
+        })
+    }
+
+    fn is_animated(&self) -> bool {
+        self.frames.len() > 1
+    }
+
+    /// The size the frames will be displayed at: `target_size` if set, otherwise their native
+    /// pixel size.
+    fn display_size(&self) -> (u16, u16) {
+        self.target_size.unwrap_or_else(|| {
+            let first = &self.frames[0].image;
+            (first.width(), first.height())
         })
     }
 
     fn is_shown(&self) -> bool {
-        self.window.is_some()
+        self.state.is_some()
     }
 
     fn remove(&mut self, conn: &impl Connection) -> Result<()> {
         assert!(self.is_shown());
-        let window = self.window.unwrap();
+        let state = self.state.take().unwrap();
 
-        conn.unmap_window(window)?;
-        self.window = None;
+        if state.event_id != 0 {
+            conn.present_select_input(state.event_id, state.window, PresentEventMask::default())?;
+        }
+        for pixmap in state.pixmaps {
+            conn.free_pixmap(pixmap)?;
+        }
+        if let Some(colormap) = state.colormap {
+            conn.free_colormap(colormap)?;
+        }
+        conn.unmap_window(state.window)?;
 
         Ok(())
     }
@@ -177,43 +557,76 @@ impl<'a> ImageDisplay<'a> {
         }
         assert!(!self.is_shown());
 
-        // Create graphics context
-        let gc_id = conn.generate_id()?;
-        conn.create_gc(
-            gc_id,
-            screen.root,
-            &CreateGCAux::new().graphics_exposures(0),
-        )?;
-        // Create and paint pixmap
-        let pixmap_id = conn.generate_id()?;
-        conn.create_pixmap(
-            screen.root_depth,
-            pixmap_id,
-            screen.root,
-            self.image.width(),
-            self.image.height(),
-        )?;
-        self.image.put(conn, pixmap_id, gc_id, 0, 0)?;
-        // Create window
+        let (disp_width, disp_height) = self.display_size();
+        let native_size = (self.frames[0].image.width(), self.frames[0].image.height());
+        let needs_scaling = (disp_width, disp_height) != native_size;
+
+        // Pick the window's depth/visual: a 32-bit ARGB visual (with its own colormap) if the
+        // frames have an alpha channel, so a compositing window manager can blend them;
+        // otherwise the same depth/visual as the rest of the screen.
+        let (win_depth, win_visual, colormap_id) = if self.has_alpha {
+            let visual = render::find_argb_visual(screen)?;
+            let colormap_id = conn.generate_id()?;
+            conn.create_colormap(ColormapAlloc::NONE, colormap_id, screen.root, visual)?;
+            (32, visual, Some(colormap_id))
+        } else {
+            (screen.root_depth, screen.root_visual, None)
+        };
+
+        // Create window at the (possibly scaled) display size
         let win_id = conn.generate_id()?;
+        let mut win_aux = CreateWindowAux::default()
+            .border_pixel(screen.black_pixel)
+            .event_mask(EventMask::STRUCTURE_NOTIFY | EventMask::EXPOSURE);
+        if let Some(colormap_id) = colormap_id {
+            win_aux = win_aux.colormap(colormap_id);
+        }
         conn.create_window(
-            screen.root_depth,
+            win_depth,
             win_id,
             screen.root,
             0,
             0,
-            self.image.width(),
-            self.image.height(),
+            disp_width,
+            disp_height,
             0,
             WindowClass::INPUT_OUTPUT,
-            0,
-            &CreateWindowAux::default().background_pixmap(pixmap_id),
+            win_visual,
+            &win_aux,
         )?;
-        conn.reparent_window(win_id, self.parent_window, x, y)?;
 
-        // Free pixmap&gcontext
-        conn.free_pixmap(pixmap_id)?;
-        conn.free_gc(gc_id)?;
+        if self.is_animated() {
+            self.present_frames(
+                conn,
+                screen,
+                win_id,
+                win_visual,
+                colormap_id,
+                native_size,
+                needs_scaling,
+            )?;
+        } else {
+            self.paint_still_frame(
+                conn,
+                screen,
+                win_id,
+                win_visual,
+                colormap_id,
+                native_size,
+                needs_scaling,
+                (disp_width, disp_height),
+            )?;
+        }
+
+        // Watch the parent for resizes/scrolling (`ConfigureNotify`) and teardown
+        // (`DestroyNotify`), so `handle_event` can keep the overlay in sync with it.
+        conn.change_window_attributes(
+            self.parent_window,
+            &ChangeWindowAttributesAux::default()
+                .event_mask(EventMask::STRUCTURE_NOTIFY | EventMask::EXPOSURE),
+        )?;
+
+        conn.reparent_window(win_id, self.parent_window, x, y)?;
 
         // Map the window
         conn.map_window(win_id)?;
@@ -221,42 +634,546 @@ impl<'a> ImageDisplay<'a> {
         // Flush the connection
         conn.flush()?;
 
-        // Set fields
-        self.window = Some(win_id);
+        Ok(())
+    }
+
+    /// Move the image to a new `(row, col)`, remembering it so future repositioning (e.g. from
+    /// `handle_event`'s `ConfigureNotify` handling) keeps using it.
+    fn reposition(
+        &mut self,
+        conn: &impl Connection,
+        screen: &Screen,
+        row_col: (i16, i16),
+    ) -> Result<()> {
+        self.row_col = row_col;
+        let (x, y) = rowcol_to_pixels(conn, self.parent_window, row_col)?;
+        self.show_at(conn, screen, (x, y))
+    }
+
+    /// Paint the (single) frame into `win_id`'s background, scaling/alpha-compositing via
+    /// RENDER as needed. Used for plain, non-animated images.
+    #[allow(clippy::too_many_arguments)]
+    fn paint_still_frame(
+        &mut self,
+        conn: &impl Connection,
+        screen: &Screen,
+        win_id: Window,
+        win_visual: Visualid,
+        colormap_id: Option<x11rb::protocol::xproto::Colormap>,
+        native_size: (u16, u16),
+        needs_scaling: bool,
+        (disp_width, disp_height): (u16, u16),
+    ) -> Result<()> {
+        let image = &self.frames[0].image;
+
+        // Create graphics context
+        let gc_id = conn.generate_id()?;
+        conn.create_gc(
+            gc_id,
+            screen.root,
+            &CreateGCAux::new().graphics_exposures(0),
+        )?;
+        // Create and paint a pixmap, at the image's native size and depth
+        let pixmap_id = conn.generate_id()?;
+        conn.create_pixmap(
+            image.depth(),
+            pixmap_id,
+            screen.root,
+            native_size.0,
+            native_size.1,
+        )?;
+        image.put(conn, pixmap_id, gc_id, 0, 0)?;
+
+        if !self.has_alpha && !needs_scaling {
+            // No scaling and no alpha blending needed: just use the pixmap as the window's
+            // background.
+            conn.change_window_attributes(
+                win_id,
+                &ChangeWindowAttributesAux::default().background_pixmap(pixmap_id),
+            )?;
+        } else {
+            // Either scale and/or alpha-composite the pixmap into the window using RENDER,
+            // instead of resampling/blending on the CPU.
+            let src_format = if self.has_alpha {
+                render::find_argb32_format(conn)?
+            } else {
+                render::find_pict_format(conn, screen.root_visual)?
+            };
+            let dst_format = render::find_pict_format(conn, win_visual)?;
+
+            let src_picture = conn.generate_id()?;
+            conn.render_create_picture(
+                src_picture,
+                pixmap_id,
+                src_format,
+                &CreatePictureAux::default(),
+            )?;
+            let dst_picture = conn.generate_id()?;
+            conn.render_create_picture(
+                dst_picture,
+                win_id,
+                dst_format,
+                &CreatePictureAux::default(),
+            )?;
+
+            if needs_scaling {
+                conn.render_set_picture_filter(src_picture, b"bilinear", &[])?;
+
+                let sx = native_size.0 as f64 / disp_width as f64;
+                let sy = native_size.1 as f64 / disp_height as f64;
+                conn.render_set_picture_transform(
+                    src_picture,
+                    Transform {
+                        matrix11: render::to_fixed(sx),
+                        matrix12: 0,
+                        matrix13: 0,
+                        matrix21: 0,
+                        matrix22: render::to_fixed(sy),
+                        matrix23: 0,
+                        matrix31: 0,
+                        matrix32: 0,
+                        matrix33: render::to_fixed(1.0),
+                    },
+                )?;
+            }
+
+            let op = if self.has_alpha {
+                PictOp::OVER
+            } else {
+                PictOp::SRC
+            };
+            conn.render_composite(
+                op,
+                src_picture,
+                x11rb::NONE,
+                dst_picture,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                disp_width,
+                disp_height,
+            )?;
+
+            conn.render_free_picture(src_picture)?;
+            conn.render_free_picture(dst_picture)?;
+        }
+
+        // Free pixmap & gcontext
+        conn.free_pixmap(pixmap_id)?;
+        conn.free_gc(gc_id)?;
+
+        self.state = Some(ShownState {
+            window: win_id,
+            pixmaps: vec![],
+            colormap: colormap_id,
+            event_id: 0,
+            next_serial: 0,
+            current_frame: 0,
+            msc_clock: present::MscClock::new(),
+        });
 
         Ok(())
     }
 
-    fn tick(&mut self, conn: &impl Connection) -> Result<()> {
-        // TODO
-        println!("Event: {:?}", conn.wait_for_event()?);
+    /// Pre-render every frame into a display-sized, presentable pixmap, then kick off playback
+    /// via the Present extension.
+    #[allow(clippy::too_many_arguments)]
+    fn present_frames(
+        &mut self,
+        conn: &impl Connection,
+        screen: &Screen,
+        win_id: Window,
+        win_visual: Visualid,
+        colormap_id: Option<x11rb::protocol::xproto::Colormap>,
+        native_size: (u16, u16),
+        needs_scaling: bool,
+    ) -> Result<()> {
+        let (disp_width, disp_height) = self.display_size();
+        let dst_format = render::find_pict_format(conn, win_visual)?;
+        let src_format = render::find_argb32_format(conn)?;
+
+        let gc_id = conn.generate_id()?;
+        conn.create_gc(
+            gc_id,
+            screen.root,
+            &CreateGCAux::new().graphics_exposures(0),
+        )?;
+
+        let mut pixmaps = Vec::with_capacity(self.frames.len());
+        for frame in &self.frames {
+            // Native-size scratch pixmap, painted with the raw frame data.
+            let native_pixmap = conn.generate_id()?;
+            conn.create_pixmap(32, native_pixmap, screen.root, native_size.0, native_size.1)?;
+            frame.image.put(conn, native_pixmap, gc_id, 0, 0)?;
+
+            // Display-sized pixmap that actually gets presented.
+            let present_pixmap = conn.generate_id()?;
+            conn.create_pixmap(32, present_pixmap, win_id, disp_width, disp_height)?;
+
+            let src_picture = conn.generate_id()?;
+            conn.render_create_picture(
+                src_picture,
+                native_pixmap,
+                src_format,
+                &CreatePictureAux::default(),
+            )?;
+            let dst_picture = conn.generate_id()?;
+            conn.render_create_picture(
+                dst_picture,
+                present_pixmap,
+                dst_format,
+                &CreatePictureAux::default(),
+            )?;
+            if needs_scaling {
+                conn.render_set_picture_filter(src_picture, b"bilinear", &[])?;
+                let sx = native_size.0 as f64 / disp_width as f64;
+                let sy = native_size.1 as f64 / disp_height as f64;
+                conn.render_set_picture_transform(
+                    src_picture,
+                    Transform {
+                        matrix11: render::to_fixed(sx),
+                        matrix12: 0,
+                        matrix13: 0,
+                        matrix21: 0,
+                        matrix22: render::to_fixed(sy),
+                        matrix23: 0,
+                        matrix31: 0,
+                        matrix32: 0,
+                        matrix33: render::to_fixed(1.0),
+                    },
+                )?;
+            }
+            conn.render_composite(
+                // dst_picture wraps a pixmap this loop iteration just created: uninitialized
+                // server-side memory, not guaranteed to be cleared. OVER would blend onto
+                // whatever garbage is there; SRC fully overwrites it instead, same as the
+                // non-alpha case in paint_still_frame.
+                PictOp::SRC,
+                src_picture,
+                x11rb::NONE,
+                dst_picture,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                disp_width,
+                disp_height,
+            )?;
+
+            conn.render_free_picture(src_picture)?;
+            conn.render_free_picture(dst_picture)?;
+            conn.free_pixmap(native_pixmap)?;
+
+            pixmaps.push(present_pixmap);
+        }
+        conn.free_gc(gc_id)?;
+
+        present::init(conn)?;
+        let event_id = conn.generate_id()?;
+        conn.present_select_input(
+            event_id,
+            win_id,
+            PresentEventMask::COMPLETE_NOTIFY | PresentEventMask::IDLE_NOTIFY,
+        )?;
+
+        let mut state = ShownState {
+            window: win_id,
+            pixmaps,
+            colormap: colormap_id,
+            event_id,
+            next_serial: 0,
+            current_frame: 0,
+            msc_clock: present::MscClock::new(),
+        };
+        present_pixmap(conn, &mut state, 0)?;
+        self.state = Some(state);
 
         Ok(())
     }
+
+    /// Drive animation playback and react to an already-received X event for the displayed
+    /// window and its parent. Returns `true` once the parent window has been destroyed.
+    fn handle_event(
+        &mut self,
+        conn: &impl Connection,
+        screen: &Screen,
+        event: Event,
+    ) -> Result<bool> {
+        match event {
+            Event::PresentCompleteNotify(ev) if self.is_shown() => {
+                let state = self.state.as_mut().unwrap();
+                if ev.window != state.window {
+                    return Ok(false);
+                }
+                state.msc_clock.observe(ev.msc, ev.ust);
+
+                // The frame that just landed stays up for its own delay before the next one.
+                let delay = self.frames[state.current_frame].delay;
+                state.current_frame = (state.current_frame + 1) % self.frames.len();
+                let target_msc = state.msc_clock.target_msc_after(delay);
+                present_pixmap(conn, state, target_msc)?;
+            }
+            Event::PresentIdleNotify(_) => {
+                // One of our pixmaps is free again; we keep them all around for the lifetime of
+                // the animation, so there's nothing to do.
+            }
+            Event::ConfigureNotify(ev) if self.is_shown() && ev.window == self.parent_window => {
+                // The terminal moved, resized, or scrolled: recompute where the image should sit
+                // and slide the overlay window there without tearing it down.
+                let (x, y) = rowcol_to_pixels(conn, self.parent_window, self.row_col)?;
+                let window = self.state.as_ref().unwrap().window;
+                conn.configure_window(window, &ConfigureWindowAux::new().x(x as i32).y(y as i32))?;
+                conn.flush()?;
+            }
+            Event::Expose(ev)
+                if self.is_shown()
+                    && ev.window == self.state.as_ref().unwrap().window
+                    && ev.count == 0 =>
+            {
+                // Redraw from scratch; cheaper to re-run `show_at` than to keep the source
+                // pixmaps around just for the rare repaint.
+                let (x, y) = rowcol_to_pixels(conn, self.parent_window, self.row_col)?;
+                self.show_at(conn, screen, (x, y))?;
+            }
+            Event::DestroyNotify(ev) if ev.window == self.parent_window => {
+                if self.is_shown() {
+                    self.remove(conn)?;
+                }
+                return Ok(true);
+            }
+            _ => {}
+        }
+
+        Ok(false)
+    }
+}
+
+/// Present the current frame's pixmap onto the shown window, targeting the given MSC (0 means
+/// "as soon as possible").
+fn present_pixmap(conn: &impl Connection, state: &mut ShownState, target_msc: u64) -> Result<()> {
+    let serial = state.next_serial;
+    state.next_serial = state.next_serial.wrapping_add(1);
+
+    conn.present_pixmap(
+        state.window,
+        state.pixmaps[state.current_frame],
+        serial,
+        0, // valid region: everything
+        0, // update region: everything
+        0,
+        0,
+        0, // target CRTC: let the server pick
+        0, // wait fence: none
+        0, // idle fence: none
+        0, // options: none
+        target_msc,
+        0,
+        0,
+        &[],
+    )?;
+    conn.flush()?;
+
+    Ok(())
+}
+
+/// Decode `path`, handling animated GIF/APNG, alpha, and plain images the same way regardless of
+/// caller: used both by the one-shot `show` CLI mode and by the daemon's `show` socket command.
+fn load_display(
+    conn: &impl Connection,
+    screen: &Screen,
+    path: &Path,
+    window: Window,
+    target_size: Option<(u16, u16)>,
+    background: Option<Rgba<u8>>,
+    row_col: (i16, i16),
+) -> Result<ImageDisplay<'static>> {
+    if let Some(frames) = anim::decode_frames(path)? {
+        let frames = match background {
+            // Flatten each frame onto the background, same as the still-image path below; the
+            // result is still an (opaque) RgbaImage, so it plays back through the same
+            // alpha-compositing window as a transparent animation would.
+            Some(background) => frames
+                .into_iter()
+                .map(|(image, delay)| (flatten_onto_background(&image, background), delay))
+                .collect(),
+            None => frames,
+        };
+        return ImageDisplay::new_animated(frames, window, target_size, row_col);
+    }
+
+    // If it has no alpha channel, or the caller asked to flatten it onto a solid background, use
+    // the plain RGB path; otherwise keep its alpha channel and let RENDER composite it over the
+    // terminal.
+    let decoded = ImageReader::open(path)?.decode()?;
+    if !decoded.color().has_alpha() {
+        ImageDisplay::new(
+            conn,
+            screen,
+            decoded.to_rgb8(),
+            window,
+            target_size,
+            row_col,
+        )
+    } else if let Some(background) = background {
+        let rgba = decoded.to_rgba8();
+        let image = RgbImage::from_fn(rgba.width(), rgba.height(), |px, py| {
+            let Rgba([r, g, b, a]) = *rgba.get_pixel(px, py);
+            let a = a as u16;
+            image::Rgb([
+                blend_channel(r, background[0], a),
+                blend_channel(g, background[1], a),
+                blend_channel(b, background[2], a),
+            ])
+        });
+        ImageDisplay::new(conn, screen, image, window, target_size, row_col)
+    } else {
+        ImageDisplay::new_rgba(decoded.to_rgba8(), window, target_size, row_col)
+    }
+}
+
+#[derive(StructOpt)]
+enum Cli {
+    /// Display a single image over the focused terminal, then keep watching it until interrupted
+    Show(Opt),
+    /// Run as a background daemon, listening on a Unix socket for show/move/hide/clear commands
+    Daemon(daemon::DaemonOpt),
 }
 
 fn main() -> Result<()> {
-    let opt: Opt = Opt::from_args();
+    match Cli::from_args() {
+        Cli::Show(opt) => run_show(opt),
+        Cli::Daemon(opt) => daemon::run(opt),
+    }
+}
 
+fn run_show(opt: Opt) -> Result<()> {
     // Connect to the X server
     let (conn, screen_num) = x11rb::connect(None).with_context(|| "Couldn't connect to X")?;
     let screen = &conn.setup().roots[screen_num];
 
     // Get current window
-    let window = get_current_window_id()?;
+    let window = get_current_window_id(&conn, screen)?;
+
+    // Work out where to place the image: either the user dragged out a rectangle with
+    // `--select`, or they gave explicit ROW/COL.
+    let (row_col, selected_size) = if opt.select {
+        let (row_col, size) = select_region(&conn, screen, window)?;
+        (row_col, Some(size))
+    } else {
+        match (opt.row, opt.col) {
+            (Some(row), Some(col)) => ((row, col), None),
+            _ => bail!("ROW and COL are required unless --select is passed"),
+        }
+    };
 
     // Convert (x, y) to pixels
-    let (x, y) = rowcol_to_pixels(&conn, window, (opt.col, opt.row))?;
+    let (x, y) = rowcol_to_pixels(&conn, window, row_col)?;
+
+    // Load the image, checking first whether it's an animated GIF/APNG
+    let frames = anim::decode_frames(&opt.image_file)?;
+    let (native_width, native_height) = match &frames {
+        Some(frames) => frames[0].0.dimensions(),
+        None => ImageReader::open(&opt.image_file)?
+            .into_dimensions()
+            .with_context(|| "Couldn't read image dimensions")?,
+    };
 
-    // Load the image
-    let image: RgbImage = ImageReader::open(opt.image_file)?.decode()?.to_rgb8();
+    // Work out the target display size: a `--select` drag wins outright, otherwise fall back to
+    // `--width`/`--height`, and finally to the image's native pixel size.
+    let (pixels_per_col, pixels_per_row) = cell_pixel_size()?;
+    let target_size = selected_size.or_else(|| match (opt.width, opt.height) {
+        (None, None) => None,
+        (width, height) => Some((
+            width
+                .map(|w| w as u16 * pixels_per_col as u16)
+                .unwrap_or(native_width as u16),
+            height
+                .map(|h| h as u16 * pixels_per_row as u16)
+                .unwrap_or(native_height as u16),
+        )),
+    });
 
-    // Show the image
-    let mut display_image = ImageDisplay::new(&conn, screen, image, window)?;
+    let mut display_image = load_display(
+        &conn,
+        screen,
+        &opt.image_file,
+        window,
+        target_size,
+        opt.background,
+        row_col,
+    )?;
     display_image.show_at(&conn, screen, (x, y))?;
 
-    // Handle it
-    loop {
-        display_image.tick(&conn)?;
+    // A self-pipe so Ctrl-C can wake the poll loop below: the handler itself must be async-signal-
+    // safe, so it only writes a byte to the pipe, leaving everything else to the main loop.
+    let mut pipe_fds = [0i32; 2];
+    if unsafe { libc::pipe(pipe_fds.as_mut_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| "Couldn't create a self-pipe for SIGINT");
     }
+    let [sigint_read_fd, sigint_write_fd] = pipe_fds;
+    SIGINT_PIPE_WRITE_FD.store(sigint_write_fd, Ordering::SeqCst);
+    ctrlc::set_handler(|| {
+        let fd = SIGINT_PIPE_WRITE_FD.load(Ordering::SeqCst);
+        let byte = 0u8;
+        unsafe {
+            libc::write(fd, &byte as *const u8 as *const _, 1);
+        }
+    })
+    .with_context(|| "Couldn't install a SIGINT handler")?;
+
+    // Wait for either an X event or SIGINT, whichever comes first, so Ctrl-C is noticed even
+    // while blocked waiting on the X socket.
+    let x_fd = conn.stream().as_raw_fd();
+    let mut pollfds = [
+        libc::pollfd {
+            fd: x_fd,
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: sigint_read_fd,
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+    let interrupted = 'outer: loop {
+        pollfds[0].revents = 0;
+        pollfds[1].revents = 0;
+        let ready = unsafe { libc::poll(pollfds.as_mut_ptr(), pollfds.len() as libc::nfds_t, -1) };
+        if ready < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err).with_context(|| "poll() on the X connection and SIGINT pipe failed");
+        }
+
+        if pollfds[1].revents & libc::POLLIN != 0 {
+            break 'outer true;
+        }
+
+        if pollfds[0].revents & libc::POLLIN != 0 {
+            while let Some(event) = conn.poll_for_event()? {
+                if display_image.handle_event(&conn, screen, event)? {
+                    break 'outer false;
+                }
+            }
+        }
+    };
+
+    if interrupted {
+        eprintln!("Interrupted, cleaning up");
+    }
+
+    if display_image.is_shown() {
+        display_image.remove(&conn)?;
+    }
+
+    Ok(())
 }
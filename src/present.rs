@@ -0,0 +1,64 @@
+use anyhow::{Context, Result};
+use std::time::Duration;
+use x11rb::connection::Connection;
+use x11rb::protocol::present::ConnectionExt as _;
+
+/// Negotiate the X Present extension version this crate relies on (1.2, for `PresentPixmap`'s
+/// MSC-relative scheduling).
+pub fn init(conn: &impl Connection) -> Result<()> {
+    conn.present_query_version(1, 2)?
+        .reply()
+        .with_context(|| "Server doesn't support the Present extension")?;
+    Ok(())
+}
+
+/// Tracks the MSC/UST pairs reported by successive `CompleteNotify` events for a presented
+/// window, so we can turn a frame delay (a `Duration`) into a target MSC to hand to the next
+/// `PresentPixmap` call.
+pub struct MscClock {
+    last_msc: Option<u64>,
+    last_ust: Option<u64>,
+    /// Best current estimate of how long one MSC (roughly: one vblank) takes, in microseconds.
+    /// Refined as more `CompleteNotify` events come in; defaults to 60Hz until then.
+    interval_us: u64,
+}
+
+impl MscClock {
+    pub fn new() -> Self {
+        Self {
+            last_msc: None,
+            last_ust: None,
+            interval_us: 1_000_000 / 60,
+        }
+    }
+}
+
+impl Default for MscClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MscClock {
+    /// Feed in the `(msc, ust)` pair from a `CompleteNotifyEvent` to refine the MSC-interval
+    /// estimate.
+    pub fn observe(&mut self, msc: u64, ust: u64) {
+        if let (Some(last_msc), Some(last_ust)) = (self.last_msc, self.last_ust) {
+            let msc_delta = msc.saturating_sub(last_msc);
+            let ust_delta = ust.saturating_sub(last_ust);
+            if msc_delta > 0 {
+                self.interval_us = ust_delta / msc_delta;
+            }
+        }
+        self.last_msc = Some(msc);
+        self.last_ust = Some(ust);
+    }
+
+    /// Compute the MSC at which a frame with the given delay, presented right after the last
+    /// observed `CompleteNotify`, should land.
+    pub fn target_msc_after(&self, delay: Duration) -> u64 {
+        let last_msc = self.last_msc.unwrap_or(0);
+        let frames = (delay.as_micros() as u64 / self.interval_us.max(1)).max(1);
+        last_msc + frames
+    }
+}
@@ -0,0 +1,72 @@
+use anyhow::{Context, Result};
+use x11rb::connection::Connection;
+use x11rb::protocol::render::{ConnectionExt as _, PictType, Pictformat};
+use x11rb::protocol::xproto::{Screen, VisualClass, Visualid};
+
+/// Find the RENDER `Pictformat` that the server associates with the given visual (i.e. the one to
+/// use when wrapping a drawable using that visual in a `Picture`). Visual IDs are unique
+/// server-wide, so no screen needs to be specified.
+pub fn find_pict_format(conn: &impl Connection, visual: Visualid) -> Result<Pictformat> {
+    let formats = conn
+        .render_query_pict_formats()?
+        .reply()
+        .with_context(|| "Couldn't query RENDER pict formats")?;
+
+    formats
+        .screens
+        .iter()
+        .flat_map(|s| s.depths.iter())
+        .flat_map(|d| d.visuals.iter())
+        .find(|v| v.visual == visual)
+        .map(|v| v.format)
+        .with_context(|| format!("No RENDER pictformat for visual {:?}", visual))
+}
+
+/// Convert a floating point number to X RENDER's 16.16 fixed-point representation, as used by
+/// `SetPictureTransform`.
+pub fn to_fixed(value: f64) -> i32 {
+    (value * 65536.0).round() as i32
+}
+
+/// Find the standard 32-bit `ARGB32` `Pictformat` (8 bits each of alpha/red/green/blue, alpha at
+/// the top byte), used as the source format for images that carry an alpha channel.
+pub fn find_argb32_format(conn: &impl Connection) -> Result<Pictformat> {
+    let formats = conn
+        .render_query_pict_formats()?
+        .reply()
+        .with_context(|| "Couldn't query RENDER pict formats")?;
+
+    formats
+        .formats
+        .iter()
+        .find(|f| {
+            f.type_ == PictType::DIRECT
+                && f.depth == 32
+                && f.direct.alpha_mask == 0xff
+                && f.direct.red_mask == 0xff
+                && f.direct.green_mask == 0xff
+                && f.direct.blue_mask == 0xff
+                && f.direct.alpha_shift == 24
+                && f.direct.red_shift == 16
+                && f.direct.green_shift == 8
+                && f.direct.blue_shift == 0
+        })
+        .map(|f| f.id)
+        .with_context(|| "Server doesn't support the standard ARGB32 pict format")
+}
+
+/// Find a 32-bit TrueColor visual suitable for windows that need a real alpha channel (i.e. that
+/// a compositing window manager can blend using per-pixel alpha).
+pub fn find_argb_visual(screen: &Screen) -> Result<Visualid> {
+    screen
+        .allowed_depths
+        .iter()
+        .find(|d| d.depth == 32)
+        .and_then(|d| {
+            d.visuals
+                .iter()
+                .find(|v| v.class == VisualClass::TRUE_COLOR)
+        })
+        .map(|v| v.visual_id)
+        .with_context(|| "No 32-bit TrueColor visual available; is a compositor running?")
+}